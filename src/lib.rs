@@ -1,13 +1,26 @@
 use std::borrow::Borrow;
 use std::collections::HashMap;
-use std::collections::hash_map::Keys;
-use std::iter::Iterator;
-use std::hash::Hash;
+use std::collections::hash_map::{self, RandomState};
+use std::iter::{FromIterator, Iterator};
+use std::hash::{BuildHasher, Hash};
 use std::ops::Index;
 
+#[cfg(not(feature = "ordered"))]
+use std::collections::hash_map::Keys;
+
+#[cfg(not(feature = "ordered"))]
 pub use std::collections::hash_map::Iter as IterAll;
 pub use std::collections::hash_map::IterMut as IterAllMut;
 
+#[cfg(feature = "serde")]
+mod serde_impl;
+
+#[cfg(feature = "persistent")]
+mod persistent;
+
+#[cfg(feature = "persistent")]
+pub use persistent::PersistentMultiMap;
+
 /// A MultiMap implementation which is just a wrapper around std::collections::HashMap.
 /// See HashMap's documentation for more details.
 ///
@@ -15,7 +28,21 @@ pub use std::collections::hash_map::IterMut as IterAllMut;
 /// and some methods are new (doesn't have an equivalent in HashMap.)
 ///
 /// The MultiMap is generic for the key (K) and the value (V). Internally the values are
-/// stored in a generic Vector.
+/// stored in a generic Vector. MultiMap is also generic over the hasher type used to hash
+/// the keys (S), defaulting to `RandomState` just like `HashMap`, so a faster hasher can
+/// be plugged in with `with_hasher`/`with_capacity_and_hasher`.
+///
+/// With the `serde` feature enabled, `MultiMap` implements `Serialize` and `Deserialize`,
+/// represented as a map from each key to its full vector of values.
+///
+/// With the `ordered` feature enabled, `MultiMap` additionally remembers the order in
+/// which keys were first inserted, so `keys()`, `iter()` and `iter_all()` yield keys in
+/// that order instead of the arbitrary order of the backing `HashMap`. This also unlocks
+/// `retain()` and `sort_keys_by()`.
+///
+/// With the `persistent` feature enabled, the crate also exposes `PersistentMultiMap`,
+/// an immutable sibling of `MultiMap` with structural sharing, for snapshot-heavy or
+/// concurrent-read workloads.
 ///
 /// # Examples
 ///
@@ -64,8 +91,10 @@ pub use std::collections::hash_map::IterMut as IterAllMut;
 /// assert_eq!(map.get("key1"), Some(&42));
 /// assert_eq!(map.get_vec("key1"), Some(&vec![42, 1337]));
 /// ```
-pub struct MultiMap<K, V> {
-    inner: HashMap<K, Vec<V>>,
+pub struct MultiMap<K, V, S = RandomState> {
+    inner: HashMap<K, Vec<V>, S>,
+    #[cfg(feature = "ordered")]
+    order: Vec<K>,
 }
 
 impl<K, V> MultiMap<K, V> where K: Eq + Hash {
@@ -79,8 +108,8 @@ impl<K, V> MultiMap<K, V> where K: Eq + Hash {
     ///
     /// let mut map: MultiMap<&str, isize> = MultiMap::new();
     /// ```
-    pub fn new() -> MultiMap<K,V> {
-        MultiMap { inner: HashMap::new() }
+    pub fn new() -> MultiMap<K, V> {
+        MultiMap::from_inner(HashMap::new())
     }
 
     /// Creates an empty multimap with the given initial capacity.
@@ -92,33 +121,60 @@ impl<K, V> MultiMap<K, V> where K: Eq + Hash {
     ///
     /// let mut map: MultiMap<&str, isize> = MultiMap::with_capacity(20);
     /// ```
-    pub fn with_capacity(capacity: usize) -> MultiMap<K,V> {
-        MultiMap { inner: HashMap::with_capacity(capacity) }
+    pub fn with_capacity(capacity: usize) -> MultiMap<K, V> {
+        MultiMap::from_inner(HashMap::with_capacity(capacity))
     }
+}
 
-    /// Inserts a key-value pair into the multimap. If the key does exists in
-    /// the map then the key is pushed to that key's vector. If the key doesn't
-    /// exists in the map a new vector with the given value is inserted.
+impl<K, V, S> MultiMap<K, V, S> {
+    #[cfg(not(feature = "ordered"))]
+    fn from_inner(inner: HashMap<K, Vec<V>, S>) -> MultiMap<K, V, S> {
+        MultiMap { inner }
+    }
+
+    #[cfg(feature = "ordered")]
+    fn from_inner(inner: HashMap<K, Vec<V>, S>) -> MultiMap<K, V, S> {
+        MultiMap { inner, order: Vec::new() }
+    }
+}
+
+impl<K, V, S> MultiMap<K, V, S> where K: Eq + Hash, S: BuildHasher {
+
+    /// Creates an empty `MultiMap` which will use the given hash builder to hash keys.
     ///
     /// # Examples
     ///
     /// ```
+    /// use std::collections::hash_map::RandomState;
     /// use multimap::MultiMap;
     ///
-    /// let mut map = MultiMap::new();
-    /// map.insert("key", 42);
+    /// let s = RandomState::new();
+    /// let mut map: MultiMap<&str, isize, RandomState> = MultiMap::with_hasher(s);
+    /// map.insert("key", 1);
     /// ```
-    pub fn insert(&mut self, k: K, v: V) {
-        if self.inner.contains_key(&k) {
-            let mut values = self.inner.get_mut(&k).unwrap();
-            values.push(v);
-        }
-        else {
-            let mut values = Vec::new();
-            values.push(v);
-            self.inner.insert(k,values);
-        }
+    pub fn with_hasher(hash_builder: S) -> MultiMap<K, V, S> {
+        MultiMap::from_inner(HashMap::with_hasher(hash_builder))
+    }
+
+    /// Creates an empty `MultiMap` with the given initial capacity which will use the
+    /// given hash builder to hash keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::hash_map::RandomState;
+    /// use multimap::MultiMap;
+    ///
+    /// let s = RandomState::new();
+    /// let mut map: MultiMap<&str, isize, RandomState> = MultiMap::with_capacity_and_hasher(20, s);
+    /// map.insert("key", 1);
+    /// ```
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> MultiMap<K, V, S> {
+        MultiMap::from_inner(HashMap::with_capacity_and_hasher(capacity, hash_builder))
     }
+}
+
+impl<K, V, S> MultiMap<K, V, S> where K: Eq + Hash, S: BuildHasher {
 
     /// Returns true if the map contains a value for the specified key.
     ///
@@ -158,11 +214,8 @@ impl<K, V> MultiMap<K, V> where K: Eq + Hash {
         self.inner.len()
     }
 
-    /// Removes a key from the map, returning the vector of values at
-    /// the key if the key was previously in the map.
-    ///
-    /// The key may be any borrowed form of the map's key type, but Hash and Eq
-    /// on the borrowed form must match those for the key type.
+    /// Returns the total number of values stored in the map, across all keys. This is
+    /// distinct from `len()`, which only counts keys.
     ///
     /// # Examples
     ///
@@ -172,14 +225,12 @@ impl<K, V> MultiMap<K, V> where K: Eq + Hash {
     /// let mut map = MultiMap::new();
     /// map.insert(1, 42);
     /// map.insert(1, 1337);
-    /// assert_eq!(map.remove(&1), Some(vec![42, 1337]));
-    /// assert_eq!(map.remove(&1), None);
+    /// map.insert(2, 99);
+    /// assert_eq!(map.len(), 2);
+    /// assert_eq!(map.flat_len(), 3);
     /// ```
-    pub fn remove<Q: ?Sized>(&mut self, k: &Q) -> Option<Vec<V>>
-        where K: Borrow<Q>,
-              Q: Eq + Hash
-    {
-        self.inner.remove(k)
+    pub fn flat_len(&self) -> usize {
+        self.inner.values().map(Vec::len).sum()
     }
 
     /// Returns a reference to the first item in the vector corresponding to
@@ -309,6 +360,121 @@ impl<K, V> MultiMap<K, V> where K: Eq + Hash {
         self.inner.is_empty()
     }
 
+    /// An iterator visiting all key-value pairs in arbitrary order. The iterator returns
+    /// a reference to the key and a mutable reference to the first element in the
+    /// corresponding key's vector. Iterator element type is (&'a K, &'a mut V).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1,42);
+    /// map.insert(1,1337);
+    /// map.insert(3,2332);
+    /// map.insert(4,1991);
+    ///
+    /// for (_, value) in map.iter_mut() {
+    ///     *value *= *value;
+    /// }
+    ///
+    /// for (key, value) in map.iter() {
+    ///     println!("key: {:?}, val: {:?}", key, value);
+    /// }
+    /// ```
+    pub fn iter_mut(&mut self) -> IterMut<K, V> {
+        IterMut { inner: self.inner.iter_mut() }
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order. The iterator returns
+    /// a reference to the key and a mutable reference to the corresponding key's vector.
+    /// Iterator element type is (&'a K, &'a mut Vec<V>).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1,42);
+    /// map.insert(1,1337);
+    /// map.insert(3,2332);
+    /// map.insert(4,1991);
+    ///
+    /// for (key, values) in map.iter_all_mut() {
+    ///     for value in values.iter_mut() {
+    ///         *value = 99;
+    ///     }
+    /// }
+    ///
+    /// for (key, values) in map.iter_all() {
+    ///     println!("key: {:?}, values: {:?}", key, values);
+    /// }
+    /// ```
+    pub fn iter_all_mut(&mut self) -> IterAllMut<K, Vec<V>> {
+        self.inner.iter_mut()
+    }
+
+    /// Pushes `v` onto the vector for `k`, creating it first if `k` isn't already
+    /// present. Returns `true` if `k` was newly inserted.
+    fn insert_value(&mut self, k: K, v: V) -> bool {
+        match self.inner.entry(k) {
+            hash_map::Entry::Occupied(mut entry) => {
+                entry.get_mut().push(v);
+                false
+            }
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(vec![v]);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "ordered"))]
+impl<K, V, S> MultiMap<K, V, S> where K: Eq + Hash, S: BuildHasher {
+
+    /// Inserts a key-value pair into the multimap. If the key does exists in
+    /// the map then the key is pushed to that key's vector. If the key doesn't
+    /// exists in the map a new vector with the given value is inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert("key", 42);
+    /// ```
+    pub fn insert(&mut self, k: K, v: V) {
+        self.insert_value(k, v);
+    }
+
+    /// Removes a key from the map, returning the vector of values at
+    /// the key if the key was previously in the map.
+    ///
+    /// The key may be any borrowed form of the map's key type, but Hash and Eq
+    /// on the borrowed form must match those for the key type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// assert_eq!(map.remove(&1), Some(vec![42, 1337]));
+    /// assert_eq!(map.remove(&1), None);
+    /// ```
+    pub fn remove<Q: ?Sized>(&mut self, k: &Q) -> Option<Vec<V>>
+        where K: Borrow<Q>,
+              Q: Eq + Hash
+    {
+        self.inner.remove(k)
+    }
+
     /// Clears the map, removing all key-value pairs.
     /// Keeps the allocated memory for reuse.
     ///
@@ -371,8 +537,8 @@ impl<K, V> MultiMap<K, V> where K: Eq + Hash {
     }
 
     /// An iterator visiting all key-value pairs in arbitrary order. The iterator returns
-    /// a reference to the key and a mutable reference to the first element in the
-    /// corresponding key's vector. Iterator element type is (&'a K, &'a mut V).
+    /// a reference to the key and the corresponding key's vector.
+    /// Iterator element type is (&'a K, &'a Vec<V>).
     ///
     /// # Examples
     ///
@@ -385,21 +551,48 @@ impl<K, V> MultiMap<K, V> where K: Eq + Hash {
     /// map.insert(3,2332);
     /// map.insert(4,1991);
     ///
-    /// for (_, value) in map.iter_mut() {
-    ///     *value *= *value;
+    /// for (key, values) in map.iter_all() {
+    ///     println!("key: {:?}, values: {:?}", key, values);
     /// }
+    /// ```
+    pub fn iter_all(&self) -> IterAll<K, Vec<V>> {
+        self.inner.iter()
+    }
+
+    /// Returns the entry corresponding to the key, allowing for in-place manipulation
+    /// of the key's vector without an extra lookup.
+    ///
+    /// # Examples
     ///
-    /// for (key, value) in map.iter() {
-    ///     println!("key: {:?}, val: {:?}", key, value);
-    /// }
     /// ```
-    pub fn iter_mut(&mut self) -> IterMut<K, V> {
-        IterMut { inner: self.inner.iter_mut() }
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map: MultiMap<&str, isize> = MultiMap::new();
+    ///
+    /// map.entry("key").or_insert(1);
+    /// assert_eq!(map["key"], 1);
+    ///
+    /// map.entry("key").or_insert(2);
+    /// assert_eq!(map["key"], 1);
+    ///
+    /// map.entry("other").push(42);
+    /// assert_eq!(map.get_vec("other"), Some(&vec![42]));
+    /// ```
+    pub fn entry(&mut self, k: K) -> Entry<K, V> {
+        match self.inner.entry(k) {
+            hash_map::Entry::Occupied(entry) => Entry::Occupied(OccupiedEntry { inner: entry }),
+            hash_map::Entry::Vacant(entry) => Entry::Vacant(VacantEntry { inner: entry }),
+        }
     }
+}
 
-    /// An iterator visiting all key-value pairs in arbitrary order. The iterator returns
-    /// a reference to the key and the corresponding key's vector.
-    /// Iterator element type is (&'a K, &'a V).
+#[cfg(feature = "ordered")]
+impl<K, V, S> MultiMap<K, V, S> where K: Eq + Hash + Clone, S: BuildHasher {
+
+    /// Inserts a key-value pair into the multimap. If the key does exists in
+    /// the map then the key is pushed to that key's vector. If the key doesn't
+    /// exists in the map a new vector with the given value is inserted and the
+    /// key is appended to the insertion order.
     ///
     /// # Examples
     ///
@@ -407,22 +600,46 @@ impl<K, V> MultiMap<K, V> where K: Eq + Hash {
     /// use multimap::MultiMap;
     ///
     /// let mut map = MultiMap::new();
-    /// map.insert(1,42);
-    /// map.insert(1,1337);
-    /// map.insert(3,2332);
-    /// map.insert(4,1991);
+    /// map.insert("key", 42);
+    /// ```
+    pub fn insert(&mut self, k: K, v: V) {
+        if !self.inner.contains_key(&k) {
+            self.order.push(k.clone());
+        }
+        self.insert_value(k, v);
+    }
+
+    /// Removes a key from the map, returning the vector of values at
+    /// the key if the key was previously in the map. The key's slot in the
+    /// insertion order is also removed.
+    ///
+    /// The key may be any borrowed form of the map's key type, but Hash and Eq
+    /// on the borrowed form must match those for the key type.
+    ///
+    /// # Examples
     ///
-    /// for (key, values) in map.iter_all() {
-    ///     println!("key: {:?}, values: {:?}", key, values);
-    /// }
     /// ```
-    pub fn iter_all(&self) -> IterAll<K, Vec<V>> {
-        self.inner.iter()
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 42);
+    /// map.insert(1, 1337);
+    /// assert_eq!(map.remove(&1), Some(vec![42, 1337]));
+    /// assert_eq!(map.remove(&1), None);
+    /// ```
+    pub fn remove<Q: ?Sized>(&mut self, k: &Q) -> Option<Vec<V>>
+        where K: Borrow<Q>,
+              Q: Eq + Hash
+    {
+        let removed = self.inner.remove(k);
+        if removed.is_some() {
+            self.order.retain(|existing| existing.borrow() != k);
+        }
+        removed
     }
 
-    /// An iterator visiting all key-value pairs in arbitrary order. The iterator returns
-    /// a reference to the key and the corresponding key's vector.
-    /// Iterator element type is (&'a K, &'a V).
+    /// Clears the map, removing all key-value pairs and the insertion order.
+    /// Keeps the allocated memory for reuse.
     ///
     /// # Examples
     ///
@@ -431,43 +648,513 @@ impl<K, V> MultiMap<K, V> where K: Eq + Hash {
     ///
     /// let mut map = MultiMap::new();
     /// map.insert(1,42);
-    /// map.insert(1,1337);
-    /// map.insert(3,2332);
-    /// map.insert(4,1991);
+    /// map.clear();
+    /// assert!(map.is_empty());
+    /// ```
+    pub fn clear(&mut self) {
+        self.inner.clear();
+        self.order.clear();
+    }
+
+    /// An iterator visiting all keys in the order they were first inserted, as
+    /// rearranged by any later call to `sort_keys_by`.
+    /// Iterator element type is &'a K.
     ///
-    /// for (key, values) in map.iter_all_mut() {
-    ///     for value in values.iter_mut() {
-    ///         *value = 99;
-    ///     }
-    /// }
+    /// # Examples
     ///
-    /// for (key, values) in map.iter_all() {
-    ///     println!("key: {:?}, values: {:?}", key, values);
-    /// }
     /// ```
-    pub fn iter_all_mut(&mut self) -> IterAllMut<K, Vec<V>> {
-        self.inner.iter_mut()
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(4,1991);
+    /// map.insert(1,42);
+    /// map.insert(2,1337);
+    ///
+    /// let keys: Vec<_> = map.keys().cloned().collect();
+    /// assert_eq!(keys, vec![4, 1, 2]);
+    /// ```
+    pub fn keys(&self) -> ::std::slice::Iter<K> {
+        self.order.iter()
     }
-}
-
-impl<'a, K, V, Q: ?Sized> Index<&'a Q> for MultiMap<K, V>
-    where K: Eq + Hash + Borrow<Q>,
-          Q: Eq + Hash
-{
-    type Output = V;
 
-    fn index(&self, index: &Q) -> &V {
-        self.inner.get(index)
-            .map(|v| &v[0])
+    /// An iterator visiting all key-value pairs in the order keys were first inserted,
+    /// as rearranged by any later call to `sort_keys_by`.
+    /// The iterator returns a reference to the key and the first element in the
+    /// corresponding key's vector. Iterator element type is (&'a K, &'a V).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(4,1991);
+    /// map.insert(1,42);
+    /// map.insert(1,1337);
+    ///
+    /// let keys: Vec<_> = map.iter().map(|(k, _)| *k).collect();
+    /// assert_eq!(keys, vec![4, 1]);
+    /// ```
+    pub fn iter(&self) -> Iter<K, V, S> {
+        Iter { keys: self.order.iter(), inner: &self.inner }
+    }
+
+    /// An iterator visiting all key-value pairs in the order keys were first inserted,
+    /// as rearranged by any later call to `sort_keys_by`.
+    /// The iterator returns a reference to the key and the corresponding key's vector.
+    /// Iterator element type is (&'a K, &'a Vec<V>).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(4,1991);
+    /// map.insert(1,42);
+    /// map.insert(1,1337);
+    ///
+    /// let keys: Vec<_> = map.iter_all().map(|(k, _)| *k).collect();
+    /// assert_eq!(keys, vec![4, 1]);
+    /// ```
+    pub fn iter_all(&self) -> IterAll<K, V, S> {
+        IterAll { keys: self.order.iter(), inner: &self.inner }
+    }
+
+    /// Returns the entry corresponding to the key, allowing for in-place manipulation
+    /// of the key's vector without an extra lookup. A newly inserted key is appended
+    /// to the insertion order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map: MultiMap<&str, isize> = MultiMap::new();
+    ///
+    /// map.entry("key").or_insert(1);
+    /// assert_eq!(map["key"], 1);
+    ///
+    /// map.entry("key").or_insert(2);
+    /// assert_eq!(map["key"], 1);
+    ///
+    /// map.entry("other").push(42);
+    /// assert_eq!(map.get_vec("other"), Some(&vec![42]));
+    /// ```
+    pub fn entry(&mut self, k: K) -> Entry<K, V> {
+        match self.inner.entry(k) {
+            hash_map::Entry::Occupied(entry) => Entry::Occupied(OccupiedEntry { inner: entry }),
+            hash_map::Entry::Vacant(entry) => {
+                Entry::Vacant(VacantEntry { inner: entry, order: &mut self.order })
+            }
+        }
+    }
+
+    /// Retains only the values for which the predicate `f` returns `true`, visiting
+    /// each value in insertion order. Keys whose vector becomes empty are dropped
+    /// entirely, along with their slot in the insertion order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(1, 1);
+    /// map.insert(1, 2);
+    /// map.insert(2, 3);
+    ///
+    /// map.retain(|_, v| *v % 2 == 0);
+    /// assert_eq!(map.get_vec(&1), Some(&vec![2]));
+    /// assert_eq!(map.get_vec(&2), None);
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F) where F: FnMut(&K, &mut V) -> bool {
+        let mut emptied = Vec::new();
+        for key in &self.order {
+            if let Some(values) = self.inner.get_mut(key) {
+                let mut i = 0;
+                while i < values.len() {
+                    if f(key, &mut values[i]) {
+                        i += 1;
+                    } else {
+                        values.remove(i);
+                    }
+                }
+                if values.is_empty() {
+                    emptied.push(key.clone());
+                }
+            }
+        }
+        for key in &emptied {
+            self.inner.remove(key);
+        }
+        self.order.retain(|key| !emptied.contains(key));
+    }
+
+    /// Reorders the insertion order of the keys using the given comparator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map = MultiMap::new();
+    /// map.insert(3, "c");
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    ///
+    /// map.sort_keys_by(|a, b| a.cmp(b));
+    /// let keys: Vec<_> = map.keys().cloned().collect();
+    /// assert_eq!(keys, vec![1, 2, 3]);
+    /// ```
+    pub fn sort_keys_by<F>(&mut self, mut compare: F) where F: FnMut(&K, &K) -> ::std::cmp::Ordering {
+        self.order.sort_by(|a, b| compare(a, b));
+    }
+}
+
+/// A view into a single entry in a map, which may either be vacant or occupied.
+///
+/// This is constructed from the `entry` method on `MultiMap`.
+pub enum Entry<'a, K: 'a, V: 'a> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+#[cfg(not(feature = "ordered"))]
+impl<'a, K: 'a, V: 'a> Entry<'a, K, V> {
+    /// Ensures the key has a vector containing a single `value` if it was vacant,
+    /// then returns a mutable reference to the first element of that vector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map: MultiMap<&str, isize> = MultiMap::new();
+    /// *map.entry("key").or_insert(1) += 1;
+    /// assert_eq!(map["key"], 2);
+    /// ```
+    pub fn or_insert(self, value: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(value),
+        }
+    }
+
+    /// Ensures the key has the given `vector` if it was vacant, then returns a mutable
+    /// reference to the first element of that vector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map: MultiMap<&str, isize> = MultiMap::new();
+    /// *map.entry("key").or_insert_vec(vec![1, 2, 3]) += 1;
+    /// assert_eq!(map.get_vec("key"), Some(&vec![2, 2, 3]));
+    /// ```
+    pub fn or_insert_vec(self, vector: Vec<V>) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert_vec(vector),
+        }
+    }
+
+    /// Pushes `value` to the key's vector, creating it first if the key was vacant,
+    /// and returns a mutable reference to the whole vector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map: MultiMap<&str, isize> = MultiMap::new();
+    /// map.entry("key").push(1);
+    /// map.entry("key").push(2);
+    /// assert_eq!(map.get_vec("key"), Some(&vec![1, 2]));
+    /// ```
+    pub fn push(self, value: V) -> &'a mut Vec<V> {
+        match self {
+            Entry::Occupied(entry) => entry.push(value),
+            Entry::Vacant(entry) => entry.push(value),
+        }
+    }
+}
+
+#[cfg(feature = "ordered")]
+impl<'a, K: 'a + Clone, V: 'a> Entry<'a, K, V> {
+    /// Ensures the key has a vector containing a single `value` if it was vacant,
+    /// then returns a mutable reference to the first element of that vector. A
+    /// newly inserted key is appended to the insertion order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map: MultiMap<&str, isize> = MultiMap::new();
+    /// *map.entry("key").or_insert(1) += 1;
+    /// assert_eq!(map["key"], 2);
+    /// ```
+    pub fn or_insert(self, value: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(value),
+        }
+    }
+
+    /// Ensures the key has the given `vector` if it was vacant, then returns a mutable
+    /// reference to the first element of that vector. A newly inserted key is
+    /// appended to the insertion order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map: MultiMap<&str, isize> = MultiMap::new();
+    /// *map.entry("key").or_insert_vec(vec![1, 2, 3]) += 1;
+    /// assert_eq!(map.get_vec("key"), Some(&vec![2, 2, 3]));
+    /// ```
+    pub fn or_insert_vec(self, vector: Vec<V>) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert_vec(vector),
+        }
+    }
+
+    /// Pushes `value` to the key's vector, creating it first if the key was vacant,
+    /// and returns a mutable reference to the whole vector. A newly inserted key is
+    /// appended to the insertion order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multimap::MultiMap;
+    ///
+    /// let mut map: MultiMap<&str, isize> = MultiMap::new();
+    /// map.entry("key").push(1);
+    /// map.entry("key").push(2);
+    /// assert_eq!(map.get_vec("key"), Some(&vec![1, 2]));
+    /// ```
+    pub fn push(self, value: V) -> &'a mut Vec<V> {
+        match self {
+            Entry::Occupied(entry) => entry.push(value),
+            Entry::Vacant(entry) => entry.push(value),
+        }
+    }
+}
+
+/// A view into an occupied entry in a `MultiMap`.
+pub struct OccupiedEntry<'a, K: 'a, V: 'a> {
+    inner: hash_map::OccupiedEntry<'a, K, Vec<V>>,
+}
+
+impl<'a, K: 'a, V: 'a> OccupiedEntry<'a, K, V> {
+    /// Converts the entry into a mutable reference to the first element of the vector
+    /// in the entry with a lifetime bound to the map itself.
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.inner.into_mut()[0]
+    }
+
+    /// Appends `value` to the vector already present in the entry and returns a
+    /// mutable reference to the vector.
+    pub fn push(self, value: V) -> &'a mut Vec<V> {
+        let values = self.inner.into_mut();
+        values.push(value);
+        values
+    }
+}
+
+/// A view into a vacant entry in a `MultiMap`.
+pub struct VacantEntry<'a, K: 'a, V: 'a> {
+    inner: hash_map::VacantEntry<'a, K, Vec<V>>,
+    #[cfg(feature = "ordered")]
+    order: &'a mut Vec<K>,
+}
+
+#[cfg(not(feature = "ordered"))]
+impl<'a, K: 'a, V: 'a> VacantEntry<'a, K, V> {
+    /// Sets the entry's vector to `vec![value]` and returns a mutable reference to
+    /// that value.
+    pub fn insert(self, value: V) -> &'a mut V {
+        &mut self.inner.insert(vec![value])[0]
+    }
+
+    /// Sets the entry's vector to `vector` and returns a mutable reference to its
+    /// first element.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `vector` is empty, since every key in a `MultiMap` must have at
+    /// least one value.
+    pub fn insert_vec(self, vector: Vec<V>) -> &'a mut V {
+        assert!(!vector.is_empty(), "VacantEntry::insert_vec: vector must not be empty");
+        &mut self.inner.insert(vector)[0]
+    }
+
+    /// Inserts `vec![value]` into the entry and returns a mutable reference to it.
+    pub fn push(self, value: V) -> &'a mut Vec<V> {
+        self.inner.insert(vec![value])
+    }
+}
+
+#[cfg(feature = "ordered")]
+impl<'a, K: 'a + Clone, V: 'a> VacantEntry<'a, K, V> {
+    /// Sets the entry's vector to `vec![value]`, appends the key to the insertion
+    /// order, and returns a mutable reference to that value.
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.order.push(self.inner.key().clone());
+        &mut self.inner.insert(vec![value])[0]
+    }
+
+    /// Sets the entry's vector to `vector`, appends the key to the insertion order,
+    /// and returns a mutable reference to its first element.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `vector` is empty, since every key in a `MultiMap` must have at
+    /// least one value.
+    pub fn insert_vec(self, vector: Vec<V>) -> &'a mut V {
+        assert!(!vector.is_empty(), "VacantEntry::insert_vec: vector must not be empty");
+        self.order.push(self.inner.key().clone());
+        &mut self.inner.insert(vector)[0]
+    }
+
+    /// Inserts `vec![value]` into the entry, appends the key to the insertion order,
+    /// and returns a mutable reference to it.
+    pub fn push(self, value: V) -> &'a mut Vec<V> {
+        self.order.push(self.inner.key().clone());
+        self.inner.insert(vec![value])
+    }
+}
+
+impl<'a, K, V, S, Q: ?Sized> Index<&'a Q> for MultiMap<K, V, S>
+    where K: Eq + Hash + Borrow<Q>,
+          Q: Eq + Hash,
+          S: BuildHasher
+{
+    type Output = V;
+
+    fn index(&self, index: &Q) -> &V {
+        self.inner.get(index)
+            .map(|v| &v[0])
             .expect("no entry found for key")
     }
 }
 
+#[cfg(not(feature = "ordered"))]
+impl<K, V> FromIterator<(K, V)> for MultiMap<K, V> where K: Eq + Hash {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iterable: T) -> MultiMap<K, V> {
+        let mut map = MultiMap::new();
+        map.extend(iterable);
+        map
+    }
+}
+
+#[cfg(feature = "ordered")]
+impl<K, V> FromIterator<(K, V)> for MultiMap<K, V> where K: Eq + Hash + Clone {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iterable: T) -> MultiMap<K, V> {
+        let mut map = MultiMap::new();
+        map.extend(iterable);
+        map
+    }
+}
+
+#[cfg(not(feature = "ordered"))]
+impl<K, V, S> Extend<(K, V)> for MultiMap<K, V, S>
+    where K: Eq + Hash,
+          S: BuildHasher
+{
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iterable: T) {
+        for (k, v) in iterable {
+            self.insert(k, v);
+        }
+    }
+}
+
+#[cfg(feature = "ordered")]
+impl<K, V, S> Extend<(K, V)> for MultiMap<K, V, S>
+    where K: Eq + Hash + Clone,
+          S: BuildHasher
+{
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iterable: T) {
+        for (k, v) in iterable {
+            self.insert(k, v);
+        }
+    }
+}
+
+#[cfg(not(feature = "ordered"))]
+impl<'a, K, V, S> IntoIterator for &'a MultiMap<K, V, S> where K: Eq + Hash, S: BuildHasher {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+#[cfg(feature = "ordered")]
+impl<'a, K, V, S> IntoIterator for &'a MultiMap<K, V, S> where K: Eq + Hash + Clone, S: BuildHasher {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V, S>;
+
+    fn into_iter(self) -> Iter<'a, K, V, S> {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut MultiMap<K, V, S> where K: Eq + Hash, S: BuildHasher {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> IterMut<'a, K, V> {
+        self.iter_mut()
+    }
+}
+
+impl<K: Clone, V, S> IntoIterator for MultiMap<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        IntoIter { inner: self.inner.into_iter(), current: None }
+    }
+}
+
+/// An owning iterator over all key-value pairs of a `MultiMap`, flattening each key's
+/// vector of values. Iterator element type is (K, V).
+pub struct IntoIter<K, V> {
+    inner: hash_map::IntoIter<K, Vec<V>>,
+    current: Option<(K, ::std::vec::IntoIter<V>)>,
+}
+
+impl<K: Clone, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        loop {
+            if let Some((ref key, ref mut values)) = self.current {
+                if let Some(value) = values.next() {
+                    return Some((key.clone(), value));
+                }
+            }
+            match self.inner.next() {
+                Some((key, values)) => self.current = Some((key, values.into_iter())),
+                None => return None,
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "ordered"))]
 #[derive(Clone)]
 pub struct Iter<'a, K: 'a, V: 'a> {
     inner: IterAll<'a,K, Vec<V>>,
 }
 
+#[cfg(not(feature = "ordered"))]
 impl<'a, K, V> Iterator for Iter<'a, K, V> {
     type Item = (&'a K, &'a V);
 
@@ -478,10 +1165,55 @@ impl<'a, K, V> Iterator for Iter<'a, K, V> {
     fn size_hint(&self) -> (usize, Option<usize>) { self.inner.size_hint() }
 }
 
+#[cfg(not(feature = "ordered"))]
 impl<'a, K, V> ExactSizeIterator for Iter<'a, K, V> {
     fn len(&self) -> usize { self.inner.len() }
 }
 
+#[cfg(feature = "ordered")]
+pub struct Iter<'a, K: 'a, V: 'a, S: 'a = RandomState> {
+    keys: ::std::slice::Iter<'a, K>,
+    inner: &'a HashMap<K, Vec<V>, S>,
+}
+
+#[cfg(feature = "ordered")]
+impl<'a, K, V, S> Iterator for Iter<'a, K, V, S> where K: Eq + Hash, S: BuildHasher {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        self.keys.next().map(|k| (k, &self.inner[k][0]))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { self.keys.size_hint() }
+}
+
+#[cfg(feature = "ordered")]
+impl<'a, K, V, S> ExactSizeIterator for Iter<'a, K, V, S> where K: Eq + Hash, S: BuildHasher {
+    fn len(&self) -> usize { self.keys.len() }
+}
+
+#[cfg(feature = "ordered")]
+pub struct IterAll<'a, K: 'a, V: 'a, S: 'a = RandomState> {
+    keys: ::std::slice::Iter<'a, K>,
+    inner: &'a HashMap<K, Vec<V>, S>,
+}
+
+#[cfg(feature = "ordered")]
+impl<'a, K, V, S> Iterator for IterAll<'a, K, V, S> where K: Eq + Hash, S: BuildHasher {
+    type Item = (&'a K, &'a Vec<V>);
+
+    fn next(&mut self) -> Option<(&'a K, &'a Vec<V>)> {
+        self.keys.next().map(|k| (k, &self.inner[k]))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) { self.keys.size_hint() }
+}
+
+#[cfg(feature = "ordered")]
+impl<'a, K, V, S> ExactSizeIterator for IterAll<'a, K, V, S> where K: Eq + Hash, S: BuildHasher {
+    fn len(&self) -> usize { self.keys.len() }
+}
+
 pub struct IterMut<'a, K: 'a, V: 'a> {
     inner: IterAllMut<'a,K, Vec<V>>,
 }
@@ -501,10 +1233,17 @@ impl<'a, K, V> ExactSizeIterator for IterMut<'a, K, V> {
 }
 
 #[test]
+#[cfg(not(feature = "ordered"))]
 fn create() {
     let _: MultiMap<usize, usize> = MultiMap { inner: HashMap::new() };
 }
 
+#[test]
+#[cfg(feature = "ordered")]
+fn create() {
+    let _: MultiMap<usize, usize> = MultiMap { inner: HashMap::new(), order: Vec::new() };
+}
+
 #[test]
 fn new() {
     let _: MultiMap<usize, usize> = MultiMap::new();
@@ -687,3 +1426,196 @@ fn iter() {
     assert_eq!(iter.len(), 1);
 }
 
+#[test]
+fn entry_or_insert_vacant() {
+    let mut m: MultiMap<usize, usize> = MultiMap::new();
+    assert_eq!(*m.entry(1).or_insert(42), 42);
+    assert_eq!(m.get_vec(&1), Some(&vec![42]));
+}
+
+#[test]
+fn entry_or_insert_occupied() {
+    let mut m: MultiMap<usize, usize> = MultiMap::new();
+    m.insert(1, 42);
+    assert_eq!(*m.entry(1).or_insert(1337), 42);
+    assert_eq!(m.get_vec(&1), Some(&vec![42]));
+}
+
+#[test]
+fn entry_or_insert_vec() {
+    let mut m: MultiMap<usize, usize> = MultiMap::new();
+    m.entry(1).or_insert_vec(vec![1, 2, 3]);
+    assert_eq!(m.get_vec(&1), Some(&vec![1, 2, 3]));
+}
+
+#[test]
+#[should_panic]
+fn entry_or_insert_vec_empty_panics() {
+    let mut m: MultiMap<usize, usize> = MultiMap::new();
+    m.entry(1).or_insert_vec(vec![]);
+}
+
+#[test]
+fn entry_or_insert_vec_empty_does_not_corrupt_map() {
+    let mut m: MultiMap<usize, usize> = MultiMap::new();
+    let _ = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+        m.entry(1).or_insert_vec(vec![]);
+    }));
+    assert_eq!(m.contains_key(&1), false);
+}
+
+#[test]
+fn with_hasher() {
+    use std::collections::hash_map::RandomState;
+
+    let s = RandomState::new();
+    let mut m: MultiMap<usize, usize, RandomState> = MultiMap::with_hasher(s);
+    m.insert(1, 42);
+    assert_eq!(m.get_vec(&1), Some(&vec![42]));
+}
+
+#[test]
+fn with_capacity_and_hasher() {
+    use std::collections::hash_map::RandomState;
+
+    let s = RandomState::new();
+    let m: MultiMap<usize, usize, RandomState> = MultiMap::with_capacity_and_hasher(20, s);
+    assert!(m.capacity() >= 20);
+}
+
+#[test]
+fn entry_push() {
+    let mut m: MultiMap<usize, usize> = MultiMap::new();
+    m.entry(1).push(42);
+    m.entry(1).push(1337);
+    assert_eq!(m.get_vec(&1), Some(&vec![42, 1337]));
+}
+
+#[test]
+fn flat_len() {
+    let mut m: MultiMap<usize, usize> = MultiMap::new();
+    m.insert(1, 42);
+    m.insert(1, 1337);
+    m.insert(2, 99);
+    assert_eq!(m.len(), 2);
+    assert_eq!(m.flat_len(), 3);
+}
+
+#[test]
+#[cfg(feature = "ordered")]
+fn ordered_keys() {
+    let mut m: MultiMap<usize, usize> = MultiMap::new();
+    m.insert(4, 1);
+    m.insert(1, 2);
+    m.insert(2, 3);
+    let keys: Vec<_> = m.keys().cloned().collect();
+    assert_eq!(keys, vec![4, 1, 2]);
+}
+
+#[test]
+#[cfg(feature = "ordered")]
+fn ordered_remove_drops_from_order() {
+    let mut m: MultiMap<usize, usize> = MultiMap::new();
+    m.insert(1, 1);
+    m.insert(2, 2);
+    m.insert(3, 3);
+    m.remove(&2);
+    let keys: Vec<_> = m.keys().cloned().collect();
+    assert_eq!(keys, vec![1, 3]);
+}
+
+#[test]
+#[cfg(feature = "ordered")]
+fn ordered_retain_drops_empty_keys() {
+    let mut m: MultiMap<usize, usize> = MultiMap::new();
+    m.insert(1, 1);
+    m.insert(1, 2);
+    m.insert(2, 3);
+    m.retain(|_, v| *v % 2 == 0);
+    assert_eq!(m.get_vec(&1), Some(&vec![2]));
+    assert_eq!(m.get_vec(&2), None);
+    let keys: Vec<_> = m.keys().cloned().collect();
+    assert_eq!(keys, vec![1]);
+}
+
+#[test]
+#[cfg(feature = "ordered")]
+fn ordered_entry_not_consumed_does_not_record_order() {
+    let mut m: MultiMap<usize, usize> = MultiMap::new();
+    m.entry(1);
+    assert_eq!(m.keys().count(), 0);
+    assert!(m.iter().next().is_none());
+}
+
+#[test]
+#[cfg(feature = "ordered")]
+fn ordered_sort_keys_by() {
+    let mut m: MultiMap<usize, &str> = MultiMap::new();
+    m.insert(3, "c");
+    m.insert(1, "a");
+    m.insert(2, "b");
+    m.sort_keys_by(|a, b| a.cmp(b));
+    let keys: Vec<_> = m.keys().cloned().collect();
+    assert_eq!(keys, vec![1, 2, 3]);
+}
+
+#[test]
+fn from_iterator() {
+    let m: MultiMap<usize, usize> = vec![(1, 42), (1, 1337), (2, 99)].into_iter().collect();
+    assert_eq!(m.get_vec(&1), Some(&vec![42, 1337]));
+    assert_eq!(m.get_vec(&2), Some(&vec![99]));
+}
+
+#[test]
+fn extend() {
+    let mut m: MultiMap<usize, usize> = MultiMap::new();
+    m.insert(1, 42);
+    m.extend(vec![(1, 1337), (2, 99)]);
+    assert_eq!(m.get_vec(&1), Some(&vec![42, 1337]));
+    assert_eq!(m.get_vec(&2), Some(&vec![99]));
+}
+
+#[test]
+#[cfg(not(feature = "ordered"))]
+fn from_iterator_non_clone_key() {
+    #[derive(PartialEq, Eq, Hash)]
+    struct NoClone(usize);
+
+    let m: MultiMap<NoClone, usize> = vec![(NoClone(1), 42)].into_iter().collect();
+    assert_eq!(m.get_vec(&NoClone(1)), Some(&vec![42]));
+}
+
+#[test]
+fn into_iter_owned() {
+    let mut m: MultiMap<usize, usize> = MultiMap::new();
+    m.insert(1, 42);
+    m.insert(1, 1337);
+    m.insert(2, 99);
+
+    let mut pairs: Vec<_> = m.into_iter().collect();
+    pairs.sort();
+    assert_eq!(pairs, vec![(1, 42), (1, 1337), (2, 99)]);
+}
+
+#[test]
+fn into_iter_ref() {
+    let mut m: MultiMap<usize, usize> = MultiMap::new();
+    m.insert(1, 42);
+    m.insert(2, 99);
+
+    let mut pairs: Vec<_> = (&m).into_iter().collect();
+    pairs.sort();
+    assert_eq!(pairs, vec![(&1, &42), (&2, &99)]);
+}
+
+#[test]
+fn into_iter_mut_ref() {
+    let mut m: MultiMap<usize, usize> = MultiMap::new();
+    m.insert(1, 42);
+
+    for (_, v) in &mut m {
+        *v += 1;
+    }
+    assert_eq!(m.get(&1), Some(&43));
+}
+
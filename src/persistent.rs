@@ -0,0 +1,142 @@
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+use im::HashMap as ImHashMap;
+use im::Vector;
+
+/// A persistent, structurally-shared sibling of `MultiMap`.
+///
+/// Where `MultiMap` mutates in place, `PersistentMultiMap` never does: `update` and
+/// `without` each return a new snapshot, reusing the parts of the underlying HAMT
+/// (borrowed from the `im` crate, in the same spirit as `im-rc`) that the change didn't
+/// touch. Taking a snapshot is therefore cheap even while other readers keep using an
+/// older one, which makes the type a good fit for concurrent-read or history-keeping
+/// workloads where cloning a whole `MultiMap` on every write would be too expensive.
+///
+/// # Examples
+///
+/// ```
+/// use multimap::PersistentMultiMap;
+///
+/// let empty = PersistentMultiMap::new();
+/// let one = empty.update("key", 1);
+/// let two = one.update("key", 2);
+///
+/// // `one` is untouched by the update that produced `two`.
+/// assert_eq!(one.get_vec(&"key").unwrap().iter().collect::<Vec<_>>(), vec![&1]);
+/// assert_eq!(two.get_vec(&"key").unwrap().iter().collect::<Vec<_>>(), vec![&1, &2]);
+///
+/// let empty_again = two.without(&"key");
+/// assert_eq!(empty_again.get_vec(&"key"), None);
+/// ```
+#[derive(Clone, Debug)]
+pub struct PersistentMultiMap<K, V>
+    where K: Eq + Hash + Clone,
+          V: Clone
+{
+    inner: ImHashMap<K, Vector<V>>,
+}
+
+impl<K, V> PersistentMultiMap<K, V>
+    where K: Eq + Hash + Clone,
+          V: Clone
+{
+    /// Creates an empty `PersistentMultiMap`.
+    pub fn new() -> PersistentMultiMap<K, V> {
+        PersistentMultiMap { inner: ImHashMap::new() }
+    }
+
+    /// Returns a new snapshot with `value` appended to the vector stored at `key`,
+    /// sharing all other keys' structure with `self`.
+    pub fn update(&self, key: K, value: V) -> PersistentMultiMap<K, V> {
+        let mut values = self.inner.get(&key).cloned().unwrap_or_else(Vector::new);
+        values.push_back(value);
+        PersistentMultiMap { inner: self.inner.update(key, values) }
+    }
+
+    /// Returns a new snapshot with `key` and all of its values removed, sharing all
+    /// other keys' structure with `self`.
+    ///
+    /// The key may be any borrowed form of the map's key type, but Hash and Eq
+    /// on the borrowed form must match those for the key type.
+    pub fn without<Q: ?Sized>(&self, key: &Q) -> PersistentMultiMap<K, V>
+        where K: Borrow<Q>,
+              Q: Eq + Hash
+    {
+        PersistentMultiMap { inner: self.inner.without(key) }
+    }
+
+    /// Returns a reference to the vector of values corresponding to the key, if present.
+    ///
+    /// The key may be any borrowed form of the map's key type, but Hash and Eq
+    /// on the borrowed form must match those for the key type.
+    pub fn get_vec<Q: ?Sized>(&self, key: &Q) -> Option<&Vector<V>>
+        where K: Borrow<Q>,
+              Q: Eq + Hash
+    {
+        self.inner.get(key)
+    }
+
+    /// Returns `true` if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Returns the number of keys stored in the map.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl<K, V> Default for PersistentMultiMap<K, V>
+    where K: Eq + Hash + Clone,
+          V: Clone
+{
+    fn default() -> PersistentMultiMap<K, V> {
+        PersistentMultiMap::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PersistentMultiMap;
+
+    #[test]
+    fn update_and_get_vec() {
+        let m: PersistentMultiMap<usize, &str> = PersistentMultiMap::new();
+        let m = m.update(1, "a");
+        let m = m.update(1, "b");
+        assert_eq!(m.get_vec(&1).map(|v| v.iter().cloned().collect::<Vec<_>>()),
+                   Some(vec!["a", "b"]));
+    }
+
+    #[test]
+    fn update_preserves_old_snapshot() {
+        let before = PersistentMultiMap::new().update("key", 1);
+        let after = before.update("key", 2);
+        assert_eq!(before.get_vec(&"key").map(|v| v.iter().cloned().collect::<Vec<_>>()),
+                   Some(vec![1]));
+        assert_eq!(after.get_vec(&"key").map(|v| v.iter().cloned().collect::<Vec<_>>()),
+                   Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn without_removes_key_and_preserves_old_snapshot() {
+        let with_key = PersistentMultiMap::new().update("key", 1);
+        let without_key = with_key.without(&"key");
+        assert_eq!(without_key.get_vec(&"key"), None);
+        assert_eq!(with_key.get_vec(&"key").map(|v| v.iter().cloned().collect::<Vec<_>>()),
+                   Some(vec![1]));
+    }
+
+    #[test]
+    fn len_and_is_empty() {
+        let m = PersistentMultiMap::new();
+        assert!(m.is_empty());
+        assert_eq!(m.len(), 0);
+
+        let m = m.update(1, "a").update(2, "b");
+        assert!(!m.is_empty());
+        assert_eq!(m.len(), 2);
+    }
+}
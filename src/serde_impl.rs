@@ -0,0 +1,164 @@
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use super::MultiMap;
+
+#[cfg(not(feature = "ordered"))]
+impl<K, V, S> Serialize for MultiMap<K, V, S>
+    where K: Serialize + Eq + Hash,
+          V: Serialize,
+          S: BuildHasher
+{
+    fn serialize<T>(&self, serializer: T) -> Result<T::Ok, T::Error>
+        where T: Serializer
+    {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (k, v) in self.iter_all() {
+            map.serialize_entry(k, v)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(feature = "ordered")]
+impl<K, V, S> Serialize for MultiMap<K, V, S>
+    where K: Serialize + Eq + Hash + Clone,
+          V: Serialize,
+          S: BuildHasher
+{
+    fn serialize<T>(&self, serializer: T) -> Result<T::Ok, T::Error>
+        where T: Serializer
+    {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (k, v) in self.iter_all() {
+            map.serialize_entry(k, v)?;
+        }
+        map.end()
+    }
+}
+
+struct MultiMapVisitor<K, V, S> {
+    marker: PhantomData<MultiMap<K, V, S>>,
+}
+
+#[cfg(not(feature = "ordered"))]
+impl<'de, K, V, S> Visitor<'de> for MultiMapVisitor<K, V, S>
+    where K: Deserialize<'de> + Eq + Hash,
+          V: Deserialize<'de>,
+          S: BuildHasher + Default
+{
+    type Value = MultiMap<K, V, S>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map from keys to vectors of values")
+    }
+
+    fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+        where A: MapAccess<'de>
+    {
+        let mut map = MultiMap::with_capacity_and_hasher(
+            access.size_hint().unwrap_or(0), S::default());
+        while let Some((key, values)) = access.next_entry::<K, Vec<V>>()? {
+            if map.contains_key(&key) {
+                return Err(::serde::de::Error::custom("duplicate key found"));
+            }
+            if values.is_empty() {
+                return Err(::serde::de::Error::custom("value vector must not be empty"));
+            }
+            map.entry(key).or_insert_vec(values);
+        }
+        Ok(map)
+    }
+}
+
+#[cfg(feature = "ordered")]
+impl<'de, K, V, S> Visitor<'de> for MultiMapVisitor<K, V, S>
+    where K: Deserialize<'de> + Eq + Hash + Clone,
+          V: Deserialize<'de>,
+          S: BuildHasher + Default
+{
+    type Value = MultiMap<K, V, S>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map from keys to vectors of values")
+    }
+
+    fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+        where A: MapAccess<'de>
+    {
+        let mut map = MultiMap::with_capacity_and_hasher(
+            access.size_hint().unwrap_or(0), S::default());
+        while let Some((key, values)) = access.next_entry::<K, Vec<V>>()? {
+            if map.contains_key(&key) {
+                return Err(::serde::de::Error::custom("duplicate key found"));
+            }
+            if values.is_empty() {
+                return Err(::serde::de::Error::custom("value vector must not be empty"));
+            }
+            map.entry(key).or_insert_vec(values);
+        }
+        Ok(map)
+    }
+}
+
+#[cfg(not(feature = "ordered"))]
+impl<'de, K, V, S> Deserialize<'de> for MultiMap<K, V, S>
+    where K: Deserialize<'de> + Eq + Hash,
+          V: Deserialize<'de>,
+          S: BuildHasher + Default
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de>
+    {
+        deserializer.deserialize_map(MultiMapVisitor { marker: PhantomData })
+    }
+}
+
+#[cfg(feature = "ordered")]
+impl<'de, K, V, S> Deserialize<'de> for MultiMap<K, V, S>
+    where K: Deserialize<'de> + Eq + Hash + Clone,
+          V: Deserialize<'de>,
+          S: BuildHasher + Default
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de>
+    {
+        deserializer.deserialize_map(MultiMapVisitor { marker: PhantomData })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::MultiMap;
+
+    #[test]
+    fn roundtrip() {
+        let mut m: MultiMap<String, i32> = MultiMap::new();
+        m.insert("a".to_string(), 1);
+        m.insert("a".to_string(), 2);
+        m.insert("b".to_string(), 3);
+
+        let json = ::serde_json::to_string(&m).unwrap();
+        let back: MultiMap<String, i32> = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(back.get_vec("a"), Some(&vec![1, 2]));
+        assert_eq!(back.get_vec("b"), Some(&vec![3]));
+    }
+
+    #[test]
+    fn duplicate_key_rejected() {
+        let res: Result<MultiMap<String, i32>, _> =
+            ::serde_json::from_str(r#"{"a": [1], "a": [2]}"#);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn empty_value_vector_rejected() {
+        let res: Result<MultiMap<String, i32>, _> =
+            ::serde_json::from_str(r#"{"a": []}"#);
+        assert!(res.is_err());
+    }
+}